@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::program::{Instruction, Operand, Register, OUTPUT_REGISTER};
+
+/// The registers whose prior values the instruction's result depends on.
+/// `mul r 0` always produces zero, so it doesn't depend on `r`.
+fn registers_read(instr: &Instruction) -> Vec<Register> {
+    match *instr {
+        Instruction::Input(_) => vec![],
+        Instruction::Mul(_, Operand::Literal(0)) => vec![],
+        _ => {
+            let mut registers = vec![instr.destination()];
+            if let Some(Operand::Register(r)) = instr.operand() {
+                registers.push(r);
+            }
+            registers
+        }
+    }
+}
+
+/// The index of the last input whose value can affect the output register, if any.
+/// Inputs are numbered in the order the program reads them, starting from 0.
+///
+/// If this is less than the index of the program's last input, the inputs after it
+/// cannot affect the output: the output's value is fully determined before they are read.
+pub fn last_relevant_input(program: &[Instruction]) -> Option<usize> {
+    let mut remaining_inputs = program
+        .iter()
+        .filter(|instr| matches!(instr, Instruction::Input(_)))
+        .count();
+
+    let mut live = [false; 4];
+    live[OUTPUT_REGISTER.0] = true;
+
+    for instr in program.iter().rev() {
+        let destination = instr.destination();
+        if let Instruction::Input(_) = instr {
+            remaining_inputs -= 1;
+            if live[destination.0] {
+                return Some(remaining_inputs);
+            }
+        }
+
+        if live[destination.0] {
+            live[destination.0] = false;
+            for register in registers_read(instr) {
+                live[register.0] = true;
+            }
+        }
+    }
+
+    None
+}
+
+/// For each input, the registers whose final values depend on it.
+/// Inputs are numbered in the order the program reads them, starting from 0.
+/// Inputs that don't influence any register map to an empty set.
+pub fn input_influence_map(program: &[Instruction]) -> BTreeMap<usize, BTreeSet<Register>> {
+    let mut register_inputs: [BTreeSet<usize>; 4] = Default::default();
+    let mut next_input = 0;
+
+    for instr in program {
+        let destination = instr.destination();
+        let dependencies = match instr {
+            Instruction::Input(_) => {
+                next_input += 1;
+                BTreeSet::from([next_input - 1])
+            }
+            _ => registers_read(instr)
+                .into_iter()
+                .flat_map(|register| register_inputs[register.0].iter().copied())
+                .collect(),
+        };
+        register_inputs[destination.0] = dependencies;
+    }
+
+    let mut influence: BTreeMap<usize, BTreeSet<Register>> = (0..next_input)
+        .map(|input| (input, BTreeSet::new()))
+        .collect();
+    for register in Register::ALL {
+        for input in &register_inputs[register.0] {
+            influence.entry(*input).or_default().insert(register);
+        }
+    }
+    influence
+}
+
+/// The number of instructions on the longest chain of data dependencies
+/// that ends in the output register's final value.
+/// Input instructions and `mul r 0` start a new chain, since their results
+/// don't depend on any prior register value.
+pub fn critical_path_length(program: &[Instruction]) -> usize {
+    let mut chain_lengths = [0usize; 4];
+
+    for instr in program {
+        let longest_read_chain = registers_read(instr)
+            .into_iter()
+            .map(|register| chain_lengths[register.0])
+            .max()
+            .unwrap_or(0);
+        chain_lengths[instr.destination().0] = longest_read_chain + 1;
+    }
+
+    chain_lengths[OUTPUT_REGISTER.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{parser::parse_program, program::Register};
+
+    use super::{critical_path_length, input_influence_map, last_relevant_input};
+
+    #[test]
+    fn final_block_not_touching_output_is_irrelevant() {
+        let program = parse_program("inp w\nadd z w\ninp x\nmul x 2\nadd y x\n").unwrap();
+        assert_eq!(Some(0), last_relevant_input(&program));
+    }
+
+    #[test]
+    fn overwritten_inputs_are_irrelevant() {
+        let program = parse_program("inp z\ninp w\nmul z 0\nadd z w\ninp w\n").unwrap();
+        assert_eq!(Some(1), last_relevant_input(&program));
+
+        let program = parse_program("inp z\nmul z 0\nadd z 1\n").unwrap();
+        assert_eq!(None, last_relevant_input(&program));
+    }
+
+    #[test]
+    fn every_aoc_challenge_input_is_relevant() {
+        let program = parse_program(include_str!("../sample_programs/aoc_challenge.txt")).unwrap();
+        assert_eq!(Some(13), last_relevant_input(&program));
+    }
+
+    #[test]
+    fn inputs_influence_only_the_registers_they_flow_into() {
+        let program = parse_program("inp w\nadd x w\ninp y\nmul y 0\ninp z\nadd z x\n").unwrap();
+        let [w, x, _, z] = Register::ALL;
+        let influence = input_influence_map(&program);
+        assert_eq!(
+            vec![
+                (0, BTreeSet::from([w, x, z])),
+                (1, BTreeSet::new()),
+                (2, BTreeSet::from([z])),
+            ],
+            influence.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn critical_path_of_known_dependency_chain() {
+        // inp w -> add x w -> mul x 2 -> add z x; the add to y is off the path.
+        let program = parse_program("inp w\nadd x w\nmul x 2\nadd y 1\nadd z x\n").unwrap();
+        assert_eq!(4, critical_path_length(&program));
+    }
+
+    #[test]
+    fn clearing_starts_a_new_chain() {
+        let program = parse_program("inp z\nadd z 1\nmul z 0\nadd z 1\n").unwrap();
+        assert_eq!(2, critical_path_length(&program));
+        assert_eq!(0, critical_path_length(&[]));
+    }
+}
@@ -0,0 +1,284 @@
+//! A compact binary encoding for MONAD programs.
+//!
+//! Each instruction is encoded as:
+//! - one opcode byte: 0 = inp, 1 = add, 2 = mul, 3 = div, 4 = mod, 5 = eql;
+//! - one byte holding the destination register's index;
+//! - for all opcodes but inp, the operand:
+//!   - a register operand is the byte 0 followed by the register's index byte;
+//!   - a literal operand is the byte 1 followed by the zigzag-encoded value as an LEB128 varint.
+
+use std::fmt::Display;
+
+use crate::program::{Instruction, Operand, Register};
+
+const OPERAND_REGISTER: u8 = 0;
+const OPERAND_LITERAL: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    InvalidOpcode { offset: usize, byte: u8 },
+    InvalidRegister { offset: usize, byte: u8 },
+    InvalidOperandKind { offset: usize, byte: u8 },
+    LiteralTooLarge { offset: usize },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::InvalidOpcode { offset, byte } => {
+                write!(f, "invalid opcode {} at offset {}", byte, offset)
+            }
+            DecodeError::InvalidRegister { offset, byte } => {
+                write!(f, "invalid register {} at offset {}", byte, offset)
+            }
+            DecodeError::InvalidOperandKind { offset, byte } => {
+                write!(f, "invalid operand kind {} at offset {}", byte, offset)
+            }
+            DecodeError::LiteralTooLarge { offset } => {
+                write!(f, "literal at offset {} does not fit in an i64", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn opcode(instr: &Instruction) -> u8 {
+    match instr {
+        Instruction::Input(_) => 0,
+        Instruction::Add(..) => 1,
+        Instruction::Mul(..) => 2,
+        Instruction::Div(..) => 3,
+        Instruction::Mod(..) => 4,
+        Instruction::Equal(..) => 5,
+    }
+}
+
+fn register_byte(register: Register) -> u8 {
+    u8::try_from(register.0).unwrap_or_else(|_| unreachable!("{:?}", register))
+}
+
+pub fn to_bytes(program: &[Instruction]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for instr in program {
+        bytes.push(opcode(instr));
+        bytes.push(register_byte(instr.destination()));
+        match instr.operand() {
+            None => {}
+            Some(Operand::Register(r)) => {
+                bytes.push(OPERAND_REGISTER);
+                bytes.push(register_byte(r));
+            }
+            Some(Operand::Literal(value)) => {
+                bytes.push(OPERAND_LITERAL);
+                let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+                loop {
+                    let low_bits = (zigzag & 0x7f) as u8;
+                    zigzag >>= 7;
+                    if zigzag == 0 {
+                        bytes.push(low_bits);
+                        break;
+                    }
+                    bytes.push(low_bits | 0x80);
+                }
+            }
+        }
+    }
+    bytes
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn next_byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.offset)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn register(&mut self) -> Result<Register, DecodeError> {
+        let offset = self.offset;
+        let byte = self.next_byte()?;
+        if usize::from(byte) < Register::ALL.len() {
+            Ok(Register(byte.into()))
+        } else {
+            Err(DecodeError::InvalidRegister { offset, byte })
+        }
+    }
+
+    fn literal(&mut self) -> Result<i64, DecodeError> {
+        let offset = self.offset;
+        let mut zigzag: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.next_byte()?;
+            if shift >= 64 || (shift == 63 && byte & 0x7e != 0) {
+                return Err(DecodeError::LiteralTooLarge { offset });
+            }
+            zigzag |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+    }
+
+    fn operand(&mut self) -> Result<Operand, DecodeError> {
+        let offset = self.offset;
+        match self.next_byte()? {
+            OPERAND_REGISTER => Ok(Operand::Register(self.register()?)),
+            OPERAND_LITERAL => Ok(Operand::Literal(self.literal()?)),
+            byte => Err(DecodeError::InvalidOperandKind { offset, byte }),
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let offset = self.offset;
+        let opcode = self.next_byte()?;
+        let register = self.register()?;
+        let instr = match opcode {
+            0 => Instruction::Input(register),
+            1 => Instruction::Add(register, self.operand()?),
+            2 => Instruction::Mul(register, self.operand()?),
+            3 => Instruction::Div(register, self.operand()?),
+            4 => Instruction::Mod(register, self.operand()?),
+            5 => Instruction::Equal(register, self.operand()?),
+            byte => return Err(DecodeError::InvalidOpcode { offset, byte }),
+        };
+        Ok(instr)
+    }
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    from_bytes_prefix(bytes, usize::MAX)
+}
+
+/// Decode only the first `limit` instructions of a program.
+/// Bytes after the limit is reached are not decoded, so they can't cause errors.
+pub fn from_bytes_prefix(bytes: &[u8], limit: usize) -> Result<Vec<Instruction>, DecodeError> {
+    let mut decoder = Decoder { bytes, offset: 0 };
+    let mut program = vec![];
+    while decoder.offset < bytes.len() && program.len() < limit {
+        program.push(decoder.instruction()?);
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::program::{Instruction, Operand, Register};
+
+    use super::{from_bytes, from_bytes_prefix, to_bytes, DecodeError};
+
+    #[test]
+    fn round_trip_every_opcode_and_operand() {
+        let literals = [0, 1, -1, 63, -64, 64, i64::MIN, i64::MAX];
+        let operands = literals
+            .into_iter()
+            .map(Operand::Literal)
+            .chain(Register::ALL.into_iter().map(Operand::Register));
+
+        let mut program = vec![];
+        for (index, operand) in operands.enumerate() {
+            let register = Register::ALL[index % Register::ALL.len()];
+            program.extend([
+                Instruction::Input(register),
+                Instruction::Add(register, operand),
+                Instruction::Mul(register, operand),
+                Instruction::Div(register, operand),
+                Instruction::Mod(register, operand),
+                Instruction::Equal(register, operand),
+            ]);
+        }
+
+        assert_eq!(Ok(program.clone()), from_bytes(&to_bytes(&program)));
+    }
+
+    #[test]
+    fn literal_encoding() {
+        let encode_add_w =
+            |value| to_bytes(&[Instruction::Add(Register(0), Operand::Literal(value))]);
+        assert_eq!(vec![1, 0, 1, 0], encode_add_w(0));
+        assert_eq!(vec![1, 0, 1, 1], encode_add_w(-1));
+        assert_eq!(vec![1, 0, 1, 2], encode_add_w(1));
+        assert_eq!(vec![1, 0, 1, 0x80, 0x01], encode_add_w(64));
+        assert_eq!(13, encode_add_w(i64::MIN).len());
+        assert_eq!(13, encode_add_w(i64::MAX).len());
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        assert_eq!(Err(DecodeError::UnexpectedEnd), from_bytes(&[1]));
+        assert_eq!(Err(DecodeError::UnexpectedEnd), from_bytes(&[1, 0]));
+        assert_eq!(Err(DecodeError::UnexpectedEnd), from_bytes(&[1, 0, 1]));
+        assert_eq!(
+            Err(DecodeError::UnexpectedEnd),
+            from_bytes(&[1, 0, 1, 0x80])
+        );
+    }
+
+    #[test]
+    fn overlong_varint_is_rejected() {
+        let mut bytes = vec![1, 0, 1];
+        bytes.extend([0x80; 10]);
+        bytes.push(0x00);
+        assert_eq!(
+            Err(DecodeError::LiteralTooLarge { offset: 3 }),
+            from_bytes(&bytes)
+        );
+
+        // Ten bytes, but the last one sets bits beyond the 64th.
+        let mut bytes = vec![1, 0, 1];
+        bytes.extend([0xff; 9]);
+        bytes.push(0x02);
+        assert_eq!(
+            Err(DecodeError::LiteralTooLarge { offset: 3 }),
+            from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_rejected() {
+        assert_eq!(
+            Err(DecodeError::InvalidOpcode { offset: 2, byte: 6 }),
+            from_bytes(&[0, 0, 6, 0])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidRegister { offset: 1, byte: 4 }),
+            from_bytes(&[0, 4])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidRegister { offset: 3, byte: 9 }),
+            from_bytes(&[1, 0, 0, 9])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidOperandKind { offset: 2, byte: 2 }),
+            from_bytes(&[1, 0, 2, 0])
+        );
+    }
+
+    #[test]
+    fn bytes_past_the_limit_are_not_decoded() {
+        let program = [
+            Instruction::Input(Register(0)),
+            Instruction::Add(Register(3), Operand::Register(Register(0))),
+        ];
+        let mut bytes = to_bytes(&program);
+        bytes.push(9);
+        assert_eq!(Ok(program[..1].to_vec()), from_bytes_prefix(&bytes, 1));
+        assert_eq!(Ok(program.to_vec()), from_bytes_prefix(&bytes, 2));
+        assert_eq!(
+            Err(DecodeError::UnexpectedEnd),
+            from_bytes_prefix(&bytes, 3)
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::program::{Instruction, Operand, Register};
+
+/// Split a program into blocks that each begin with an input instruction.
+/// Any instructions before the first input instruction form their own leading block.
+pub fn split_into_input_blocks(program: &[Instruction]) -> Vec<&[Instruction]> {
+    let mut blocks = vec![];
+    let mut block_start = 0;
+    for (index, instr) in program.iter().enumerate() {
+        if matches!(instr, Instruction::Input(_)) && index > block_start {
+            blocks.push(&program[block_start..index]);
+            block_start = index;
+        }
+    }
+    if block_start < program.len() {
+        blocks.push(&program[block_start..]);
+    }
+    blocks
+}
+
+/// The constants that differ between the 14 blocks of an Advent of Code 2021 Day 24 program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockParams {
+    pub z_divisor: i64, // e.g. the 26 in div z 26
+    pub x_offset: i64,  // e.g. the -8 in add x -8
+    pub y_offset: i64,  // e.g. the 3 in add y 3
+}
+
+impl BlockParams {
+    /// The instructions of the Advent of Code block with these parameters.
+    pub fn to_instructions(self) -> Vec<Instruction> {
+        let [w, x, y, z] = Register::ALL;
+        vec![
+            Instruction::Input(w),
+            Instruction::Mul(x, Operand::Literal(0)),
+            Instruction::Add(x, Operand::Register(z)),
+            Instruction::Mod(x, Operand::Literal(26)),
+            Instruction::Div(z, Operand::Literal(self.z_divisor)),
+            Instruction::Add(x, Operand::Literal(self.x_offset)),
+            Instruction::Equal(x, Operand::Register(w)),
+            Instruction::Equal(x, Operand::Literal(0)),
+            Instruction::Mul(y, Operand::Literal(0)),
+            Instruction::Add(y, Operand::Literal(25)),
+            Instruction::Mul(y, Operand::Register(x)),
+            Instruction::Add(y, Operand::Literal(1)),
+            Instruction::Mul(z, Operand::Register(y)),
+            Instruction::Mul(y, Operand::Literal(0)),
+            Instruction::Add(y, Operand::Register(w)),
+            Instruction::Add(y, Operand::Literal(self.y_offset)),
+            Instruction::Mul(y, Operand::Register(x)),
+            Instruction::Add(z, Operand::Register(y)),
+        ]
+    }
+}
+
+/// Extract the parameters of a block, if it has the exact shape of an Advent of Code block.
+pub fn extract_block_params(block: &[Instruction]) -> Option<BlockParams> {
+    let literal_at = |index: usize| match block.get(index)?.operand()? {
+        Operand::Literal(value) => Some(value),
+        Operand::Register(_) => None,
+    };
+
+    let params = BlockParams {
+        z_divisor: literal_at(4)?,
+        x_offset: literal_at(5)?,
+        y_offset: literal_at(15)?,
+    };
+
+    (params.to_instructions() == block).then_some(params)
+}
+
+/// The role a block plays in treating `z` as a base-26 stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    Push,  // div z 1: always pushes the input digit plus y_offset
+    Pop,   // div z 26: pops, and must not push again for the model number to be valid
+    Other, // not an Advent of Code block
+}
+
+impl From<Option<BlockParams>> for BlockKind {
+    fn from(params: Option<BlockParams>) -> Self {
+        match params.map(|p| p.z_divisor) {
+            Some(1) => BlockKind::Push,
+            Some(26) => BlockKind::Pop,
+            _ => BlockKind::Other,
+        }
+    }
+}
+
+/// Classify each input block of the program as a push or pop of the `z` stack.
+pub fn classify_blocks(program: &[Instruction]) -> Vec<BlockKind> {
+    split_into_input_blocks(program)
+        .into_iter()
+        .map(|block| extract_block_params(block).into())
+        .collect()
+}
+
+/// Whether the blocks form a balanced sequence of stack operations:
+/// every pop has a matching earlier push, and the stack is empty at the end.
+/// A model number can only bring `z` back to zero if this holds.
+pub fn is_stack_balanced(kinds: &[BlockKind]) -> bool {
+    let mut depth: usize = 0;
+    for kind in kinds {
+        match kind {
+            BlockKind::Push => depth += 1,
+            BlockKind::Pop => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return false,
+            },
+            BlockKind::Other => return false,
+        }
+    }
+    depth == 0
+}
+
+/// Find pairs of adjacent input blocks that consist of identical instructions,
+/// reported as the indices of the two blocks.
+pub fn find_duplicate_blocks(program: &[Instruction]) -> Vec<(usize, usize)> {
+    split_into_input_blocks(program)
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] == pair[1])
+        .map(|(index, _)| (index, index + 1))
+        .collect()
+}
+
+/// An upper bound on the value of `z` at any point while running the program on
+/// a valid model number, computed from the parameters of its blocks.
+///
+/// Push blocks compute `z = z * 26 + (digit + y_offset)` with `digit <= 9`. In a valid
+/// model number, pop blocks must not push again, so they compute `z = z / 26`.
+/// Returns `None` if the program isn't made of Advent of Code blocks whose pushes and pops
+/// balance out, since then no model number is valid and the pop assumption doesn't hold.
+pub fn max_z_bound(program: &[Instruction]) -> Option<i64> {
+    if !is_stack_balanced(&classify_blocks(program)) {
+        return None;
+    }
+
+    let mut z_bound: i64 = 0;
+    let mut max_z_bound = z_bound;
+    for block in split_into_input_blocks(program) {
+        let params = extract_block_params(block)?;
+        match BlockKind::from(Some(params)) {
+            BlockKind::Push => {
+                let max_pushed_value = params.y_offset.saturating_add(9).max(0);
+                z_bound = z_bound.saturating_mul(26).saturating_add(max_pushed_value);
+            }
+            BlockKind::Pop => z_bound /= 26,
+            BlockKind::Other => return None,
+        }
+        max_z_bound = max_z_bound.max(z_bound);
+    }
+    Some(max_z_bound)
+}
+
+/// A three-instruction sequence computing a boolean "not equal" into `register`:
+/// `add r <addend>; eql r <compared_with>; eql r 0`.
+/// Afterward, `register` is 1 if `r + addend != compared_with` and 0 otherwise,
+/// so its value is always in the range `0..=1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompoundBool {
+    pub start_index: usize, // index of the add instruction within the block
+    pub register: Register,
+    pub addend: Operand,
+    pub compared_with: Operand,
+}
+
+/// Find all compound "not equal" boolean computations in the block.
+pub fn detect_compound_booleans(block: &[Instruction]) -> Vec<CompoundBool> {
+    block
+        .windows(3)
+        .enumerate()
+        .filter_map(|(start_index, window)| match *window {
+            [
+                Instruction::Add(r1, addend),
+                Instruction::Equal(r2, compared_with),
+                Instruction::Equal(r3, Operand::Literal(0)),
+            ] if r1 == r2 && r2 == r3 => {
+                Some(CompoundBool {
+                    start_index,
+                    register: r1,
+                    addend,
+                    compared_with,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_program,
+        program::{Instruction, Operand, Register},
+    };
+
+    use super::{
+        classify_blocks, detect_compound_booleans, extract_block_params, find_duplicate_blocks,
+        is_stack_balanced, max_z_bound, split_into_input_blocks, BlockKind, BlockParams,
+        CompoundBool,
+    };
+
+    const AOC_CHALLENGE: &str = include_str!("../sample_programs/aoc_challenge.txt");
+
+    fn program_from_params(params: &[(i64, i64, i64)]) -> Vec<Instruction> {
+        params
+            .iter()
+            .flat_map(|&(z_divisor, x_offset, y_offset)| {
+                BlockParams {
+                    z_divisor,
+                    x_offset,
+                    y_offset,
+                }
+                .to_instructions()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_keeps_leading_non_input_instructions_as_own_block() {
+        let program = parse_program("add z 1\ninp w\nadd x w\ninp y\n").unwrap();
+        let blocks = split_into_input_blocks(&program);
+        assert_eq!(vec![&program[0..1], &program[1..3], &program[3..4]], blocks);
+    }
+
+    #[test]
+    fn extracts_params_of_aoc_block() {
+        let program = parse_program(AOC_CHALLENGE).unwrap();
+        let first_block = split_into_input_blocks(&program)[0];
+        assert_eq!(
+            Some(BlockParams {
+                z_divisor: 1,
+                x_offset: 11,
+                y_offset: 3
+            }),
+            extract_block_params(first_block)
+        );
+    }
+
+    #[test]
+    fn extract_params_rejects_other_blocks() {
+        let mut block = program_from_params(&[(26, -8, 5)]);
+        block[6] = Instruction::Add(block[6].destination(), block[6].operand().unwrap());
+        assert_eq!(None, extract_block_params(&block));
+        assert_eq!(None, extract_block_params(&block[..17]));
+    }
+
+    #[test]
+    fn classifies_known_push_pop_program() {
+        let program = program_from_params(&[(1, 12, 4), (1, 11, 10), (26, -3, 5), (26, -8, 1)]);
+        let kinds = classify_blocks(&program);
+        assert_eq!(
+            vec![
+                BlockKind::Push,
+                BlockKind::Push,
+                BlockKind::Pop,
+                BlockKind::Pop
+            ],
+            kinds
+        );
+        assert!(is_stack_balanced(&kinds));
+    }
+
+    #[test]
+    fn aoc_challenge_is_balanced() {
+        let program = parse_program(AOC_CHALLENGE).unwrap();
+        let kinds = classify_blocks(&program);
+        assert_eq!(14, kinds.len());
+        assert_eq!(7, kinds.iter().filter(|k| **k == BlockKind::Push).count());
+        assert!(is_stack_balanced(&kinds));
+    }
+
+    #[test]
+    fn unbalanced_stacks() {
+        use BlockKind::*;
+        assert!(!is_stack_balanced(&[Pop, Push]));
+        assert!(!is_stack_balanced(&[Push, Push, Pop]));
+        assert!(!is_stack_balanced(&[Push, Other, Pop]));
+        assert!(is_stack_balanced(&[]));
+    }
+
+    #[test]
+    fn finds_adjacent_identical_blocks() {
+        let program = program_from_params(&[(1, 12, 4), (1, 12, 4), (26, -3, 5), (1, 12, 4)]);
+        assert_eq!(vec![(0, 1)], find_duplicate_blocks(&program));
+    }
+
+    #[test]
+    fn no_duplicates_among_aoc_challenge_blocks() {
+        let program = parse_program(AOC_CHALLENGE).unwrap();
+        assert!(find_duplicate_blocks(&program).is_empty());
+    }
+
+    #[test]
+    fn max_z_bound_of_known_pushes() {
+        // After the first push, z <= 9 + 4 = 13. After the second, z <= 13 * 26 + 9 + 10 = 357.
+        let program = program_from_params(&[(1, 12, 4), (1, 11, 10), (26, -3, 5), (26, -8, 1)]);
+        assert_eq!(Some(357), max_z_bound(&program));
+    }
+
+    #[test]
+    fn max_z_bound_requires_balanced_stack() {
+        let program = program_from_params(&[(1, 12, 4), (1, 11, 10), (26, -3, 5)]);
+        assert_eq!(None, max_z_bound(&program));
+
+        let program = program_from_params(&[(26, -3, 5), (1, 12, 4)]);
+        assert_eq!(None, max_z_bound(&program));
+    }
+
+    #[test]
+    fn detects_canonical_not_equal_sequence() {
+        let block = parse_program("inp w\nadd x 11\neql x w\neql x 0\nadd y x\n").unwrap();
+        assert_eq!(
+            vec![CompoundBool {
+                start_index: 1,
+                register: Register(1),
+                addend: Operand::Literal(11),
+                compared_with: Operand::Register(Register(0)),
+            }],
+            detect_compound_booleans(&block)
+        );
+    }
+
+    #[test]
+    fn compound_boolean_needs_one_register_and_final_comparison_with_zero() {
+        for text in [
+            "add x 11\neql y w\neql y 0\n",
+            "add x 11\neql x w\neql x 1\n",
+            "add x 11\neql x w\nadd x 0\neql x 0\n",
+        ] {
+            let block = parse_program(text).unwrap();
+            assert!(detect_compound_booleans(&block).is_empty(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn every_aoc_block_has_one_compound_boolean() {
+        let program = parse_program(AOC_CHALLENGE).unwrap();
+        for block in split_into_input_blocks(&program) {
+            assert_eq!(1, detect_compound_booleans(block).len());
+        }
+    }
+}
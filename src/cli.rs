@@ -0,0 +1,341 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+pub const USAGE: &str = "\
+Usage: monad_compiler <COMMAND> <INPUT_FILE> [OPTIONS]
+
+Commands:
+  analyze    Parse and optimize each program in the file, then print the results;
+             programs are separated by blank lines or `---` lines
+  blocks     Show the push/pop structure of the program's input blocks
+  dataflow   Show how the program's inputs flow into its registers
+  count      Report the number of instructions and inputs, without building the program
+  encode     Write the program's binary encoding to stdout (needs the `binary` feature);
+             with that feature, `*.bin` input files are read in this encoding
+
+Options:
+      --limit <N>         Only process the first N instructions of each program
+      --time              Report the wall-clock time of each phase on stderr
+      --compare-passes    Report how much worse the result gets with each pass disabled
+      --skip-pass <NAME>  Don't run the named optimization pass; may be given repeatedly
+  -h, --help              Print this help message";
+
+/// The subcommands supported by the command-line interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Analyze,
+    Blocks,
+    Dataflow,
+    Count,
+    Encode,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "analyze" => Some(Command::Analyze),
+            "blocks" => Some(Command::Blocks),
+            "dataflow" => Some(Command::Dataflow),
+            "count" => Some(Command::Count),
+            "encode" => Some(Command::Encode),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Command::Analyze => "analyze",
+            Command::Blocks => "blocks",
+            Command::Dataflow => "dataflow",
+            Command::Count => "count",
+            Command::Encode => "encode",
+        }
+    }
+}
+
+/// The fully-parsed command-line arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub command: Command,
+    pub input_file: PathBuf,
+    pub limit: Option<usize>,
+    pub time: bool,
+    pub compare_passes: bool,
+    pub skip_passes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    HelpRequested,
+    MissingCommand,
+    UnknownCommand(String),
+    MissingInputFile,
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    InvalidValue {
+        flag: &'static str,
+        value: String,
+    },
+    UnexpectedArgument(String),
+    UnsupportedFlag {
+        flag: &'static str,
+        command: Command,
+    },
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::HelpRequested => write!(f, "help requested"),
+            CliError::MissingCommand => write!(f, "missing command"),
+            CliError::UnknownCommand(c) => write!(f, "unknown command: {}", c),
+            CliError::MissingInputFile => write!(f, "missing input file"),
+            CliError::UnknownFlag(flag) => write!(f, "unknown flag: {}", flag),
+            CliError::MissingValue(flag) => write!(f, "missing value for {}", flag),
+            CliError::InvalidValue { flag, value } => {
+                write!(f, "invalid value for {}: {}", flag, value)
+            }
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument: {}", arg),
+            CliError::UnsupportedFlag { flag, command } => {
+                write!(
+                    f,
+                    "{} is not supported by the {} command",
+                    flag,
+                    command.name()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Cli {
+    /// Parse the arguments that follow the executable name.
+    pub fn parse<I, S>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut command = None;
+        let mut input_file = None;
+        let mut limit = None;
+        let mut time = false;
+        let mut compare_passes = false;
+        let mut skip_passes = vec![];
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let arg = arg.as_ref();
+            match arg {
+                "-h" | "--help" => return Err(CliError::HelpRequested),
+                "--limit" => {
+                    let value = args.next().ok_or(CliError::MissingValue("--limit"))?;
+                    limit = Some(parse_flag_value("--limit", value.as_ref())?);
+                }
+                "--time" => time = true,
+                "--compare-passes" => compare_passes = true,
+                "--skip-pass" => {
+                    let value = args.next().ok_or(CliError::MissingValue("--skip-pass"))?;
+                    skip_passes.push(value.as_ref().to_string());
+                }
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(CliError::UnknownFlag(flag.to_string()));
+                }
+                positional => {
+                    if command.is_none() {
+                        command = Some(
+                            Command::from_name(positional)
+                                .ok_or_else(|| CliError::UnknownCommand(positional.to_string()))?,
+                        );
+                    } else if input_file.is_none() {
+                        input_file = Some(PathBuf::from(positional));
+                    } else {
+                        return Err(CliError::UnexpectedArgument(positional.to_string()));
+                    }
+                }
+            }
+        }
+
+        let command = command.ok_or(CliError::MissingCommand)?;
+        if command != Command::Analyze {
+            if compare_passes {
+                return Err(CliError::UnsupportedFlag {
+                    flag: "--compare-passes",
+                    command,
+                });
+            }
+            if !skip_passes.is_empty() {
+                return Err(CliError::UnsupportedFlag {
+                    flag: "--skip-pass",
+                    command,
+                });
+            }
+        }
+
+        Ok(Cli {
+            command,
+            input_file: input_file.ok_or(CliError::MissingInputFile)?,
+            limit,
+            time,
+            compare_passes,
+            skip_passes,
+        })
+    }
+}
+
+fn parse_flag_value<T: FromStr>(flag: &'static str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::InvalidValue {
+        flag,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Cli, CliError, Command};
+
+    #[test]
+    fn parses_command_and_input_file() {
+        let cli = Cli::parse(["analyze", "program.txt"]).unwrap();
+        assert_eq!(Command::Analyze, cli.command);
+        assert_eq!(PathBuf::from("program.txt"), cli.input_file);
+        assert_eq!(None, cli.limit);
+        assert!(!cli.time);
+    }
+
+    #[test]
+    fn help_is_requested() {
+        assert_eq!(Err(CliError::HelpRequested), Cli::parse(["--help"]));
+        assert_eq!(Err(CliError::HelpRequested), Cli::parse(["analyze", "-h"]));
+    }
+
+    #[test]
+    fn missing_command() {
+        assert_eq!(
+            Err(CliError::MissingCommand),
+            Cli::parse(Vec::<&str>::new())
+        );
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert_eq!(
+            Err(CliError::UnknownCommand("optimize".to_string())),
+            Cli::parse(["optimize", "program.txt"])
+        );
+    }
+
+    #[test]
+    fn missing_input_file() {
+        assert_eq!(Err(CliError::MissingInputFile), Cli::parse(["analyze"]));
+    }
+
+    #[test]
+    fn unknown_flag() {
+        assert_eq!(
+            Err(CliError::UnknownFlag("--fast".to_string())),
+            Cli::parse(["analyze", "program.txt", "--fast"])
+        );
+    }
+
+    #[test]
+    fn limit_without_value() {
+        assert_eq!(
+            Err(CliError::MissingValue("--limit")),
+            Cli::parse(["analyze", "program.txt", "--limit"])
+        );
+    }
+
+    #[test]
+    fn limit_with_non_numeric_value() {
+        assert_eq!(
+            Err(CliError::InvalidValue {
+                flag: "--limit",
+                value: "ten".to_string()
+            }),
+            Cli::parse(["analyze", "program.txt", "--limit", "ten"])
+        );
+    }
+
+    #[test]
+    fn extra_positional_argument() {
+        assert_eq!(
+            Err(CliError::UnexpectedArgument("other.txt".to_string())),
+            Cli::parse(["analyze", "program.txt", "other.txt"])
+        );
+    }
+
+    #[test]
+    fn flags_before_positional_arguments() {
+        let cli = Cli::parse(["--limit", "10", "--time", "analyze", "program.txt"]).unwrap();
+        assert_eq!(Command::Analyze, cli.command);
+        assert_eq!(PathBuf::from("program.txt"), cli.input_file);
+        assert_eq!(Some(10), cli.limit);
+        assert!(cli.time);
+    }
+
+    #[test]
+    fn count_command() {
+        let cli = Cli::parse(["count", "program.txt", "--limit", "5"]).unwrap();
+        assert_eq!(Command::Count, cli.command);
+        assert_eq!(Some(5), cli.limit);
+    }
+
+    #[test]
+    fn compare_passes_is_only_supported_by_analyze() {
+        assert!(
+            Cli::parse(["analyze", "program.txt", "--compare-passes"])
+                .unwrap()
+                .compare_passes
+        );
+        for command in [
+            Command::Blocks,
+            Command::Dataflow,
+            Command::Count,
+            Command::Encode,
+        ] {
+            let error =
+                Cli::parse([command.name(), "program.txt", "--compare-passes"]).unwrap_err();
+            assert_eq!(
+                CliError::UnsupportedFlag {
+                    flag: "--compare-passes",
+                    command
+                },
+                error
+            );
+        }
+        assert_eq!(
+            "--compare-passes is not supported by the blocks command",
+            Cli::parse(["blocks", "program.txt", "--compare-passes"])
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn skip_pass_may_be_repeated() {
+        let cli = Cli::parse([
+            "analyze",
+            "program.txt",
+            "--skip-pass",
+            "peephole",
+            "--skip-pass",
+            "other",
+        ])
+        .unwrap();
+        assert_eq!(vec!["peephole", "other"], cli.skip_passes);
+        assert_eq!(
+            Err(CliError::MissingValue("--skip-pass")),
+            Cli::parse(["analyze", "program.txt", "--skip-pass"])
+        );
+        assert_eq!(
+            Err(CliError::UnsupportedFlag {
+                flag: "--skip-pass",
+                command: Command::Blocks
+            }),
+            Cli::parse(["blocks", "program.txt", "--skip-pass", "peephole"])
+        );
+    }
+}
@@ -1,37 +1,386 @@
 #![allow(unused_imports)]
 
-use std::{env, fs};
+use std::{
+    env,
+    error::Error,
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
 
 use itertools::Itertools;
 
-use crate::{parser::parse_program, program::{Instruction, InstructionStream}};
+use crate::{
+    analysis::{critical_path_length, input_influence_map, last_relevant_input},
+    blocks::{
+        classify_blocks, detect_compound_booleans, extract_block_params, find_duplicate_blocks,
+        is_stack_balanced, max_z_bound, split_into_input_blocks, BlockKind,
+    },
+    cli::{Cli, CliError, Command, USAGE},
+    optimization::{find_constant_loads, Pipeline},
+    parser::{count_instructions, parse_program, parse_program_prefix, parse_programs, ParseError},
+    program::{program_cost, Instruction, InstructionStream},
+};
 
+mod analysis;
+#[cfg(feature = "binary")]
+mod binary;
+mod blocks;
+mod cli;
+mod optimization;
 mod parser;
 mod program;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut reversed_args: Vec<_> = args.iter().map(|x| x.as_str()).rev().collect();
+    let cli = match Cli::parse(env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(CliError::HelpRequested) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(cli) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// The contents of the input file. With the `binary` feature, a file named `*.bin`
+/// holds a single program in the binary encoding; any other file is program text.
+enum Input {
+    Text(String),
+    #[cfg(feature = "binary")]
+    Binary(Vec<u8>),
+}
+
+impl Input {
+    fn read(path: &Path) -> io::Result<Self> {
+        #[cfg(feature = "binary")]
+        if path.extension() == Some(OsStr::new("bin")) {
+            return Ok(Input::Binary(fs::read(path)?));
+        }
+        Ok(Input::Text(fs::read_to_string(path)?))
+    }
+
+    /// The input as a single program, limited to its first `limit` instructions.
+    fn program(&self, limit: Option<usize>) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        match (self, limit) {
+            (Input::Text(text), Some(limit)) => Ok(parse_program_prefix(text, limit)?),
+            (Input::Text(text), None) => Ok(parse_program(text)?),
+            #[cfg(feature = "binary")]
+            (Input::Binary(bytes), Some(limit)) => Ok(binary::from_bytes_prefix(bytes, limit)?),
+            #[cfg(feature = "binary")]
+            (Input::Binary(bytes), None) => Ok(binary::from_bytes(bytes)?),
+        }
+    }
+
+    /// Every program in the input, each limited to its first `limit` instructions.
+    fn programs(&self, limit: Option<usize>) -> Result<Vec<Vec<Instruction>>, Box<dyn Error>> {
+        match self {
+            Input::Text(text) => Ok(parse_programs(text, limit)?),
+            #[cfg(feature = "binary")]
+            Input::Binary(_) => Ok(vec![self.program(limit)?]),
+        }
+    }
+
+    /// The number of instructions and of `inp` instructions in the input.
+    fn count(&self, limit: Option<usize>) -> Result<(usize, usize), Box<dyn Error>> {
+        match self {
+            Input::Text(text) => Ok(count_instructions(text, limit)?),
+            #[cfg(feature = "binary")]
+            Input::Binary(_) => {
+                let program = self.program(limit)?;
+                let input_count = program
+                    .iter()
+                    .filter(|instr| matches!(instr, Instruction::Input(_)))
+                    .count();
+                Ok((program.len(), input_count))
+            }
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let input = Input::read(&cli.input_file)?;
+
+    let load = || -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let parse_start = Instant::now();
+        let input_program = input.program(cli.limit)?;
+        if cli.time {
+            eprintln!("parse: {:?}", parse_start.elapsed());
+        }
+        Ok(input_program)
+    };
+
+    match cli.command {
+        Command::Count => {
+            let count_start = Instant::now();
+            let (instruction_count, input_count) = input.count(cli.limit)?;
+            if cli.time {
+                eprintln!("count: {:?}", count_start.elapsed());
+            }
+            println!("{} instructions, {} inputs", instruction_count, input_count);
+        }
+        Command::Analyze => {
+            let pipeline = build_pipeline(&cli.skip_passes)?;
+            let parse_start = Instant::now();
+            let programs = input.programs(cli.limit)?;
+            if cli.time {
+                eprintln!("parse: {:?}", parse_start.elapsed());
+            }
+
+            let program_count = programs.len();
+            for (index, input_program) in programs.into_iter().enumerate() {
+                if program_count > 1 {
+                    println!("program {}:", index);
+                }
+                if cli.compare_passes {
+                    compare_passes(&pipeline, input_program, cli.time);
+                } else {
+                    analyze_program(&pipeline, input_program, cli.time);
+                }
+            }
+        }
+        Command::Blocks => print_blocks(&load()?),
+        Command::Dataflow => print_dataflow(&load()?),
+        Command::Encode => encode(&load()?, &mut io::stdout())?,
+    }
+
+    Ok(())
+}
+
+fn analyze_program(pipeline: &Pipeline, input_program: Vec<Instruction>, time: bool) {
+    let optimize_start = Instant::now();
+    let (analysis, pass_timings) = pipeline.run_timed(input_program);
+    if time {
+        print_pass_timings(pass_timings, optimize_start.elapsed());
+    }
+    println!("{:?}", analysis);
+    println!(
+        "{} instructions, total cost {}",
+        analysis.len(),
+        program_cost(&analysis)
+    );
+}
+
+fn compare_passes(pipeline: &Pipeline, input_program: Vec<Instruction>, time: bool) {
+    let optimize_start = Instant::now();
+    let (optimized, pass_timings) = pipeline.run_timed(input_program.clone());
+    if time {
+        print_pass_timings(pass_timings, optimize_start.elapsed());
+    }
+    let full_len = optimized.len();
+    println!("all passes: {} instructions", full_len);
+
+    let ablate_start = Instant::now();
+    let ablation = pipeline.ablate(&input_program);
+    if time {
+        eprintln!("compare passes total: {:?}", ablate_start.elapsed());
+    }
+    for (pass_name, len) in ablation {
+        println!(
+            "without {}: {} instructions ({:+})",
+            pass_name,
+            len,
+            len as i64 - full_len as i64
+        );
+    }
+}
+
+fn print_pass_timings(pass_timings: Vec<(&'static str, Duration)>, total: Duration) {
+    for (pass_name, duration) in pass_timings {
+        eprintln!("pass {}: {:?}", pass_name, duration);
+    }
+    eprintln!("optimize total: {:?}", total);
+}
+
+fn print_blocks(program: &[Instruction]) {
+    for (index, block) in split_into_input_blocks(program).into_iter().enumerate() {
+        match extract_block_params(block) {
+            Some(params) => println!(
+                "block {}: {:?}, div z {}, add x {}, add y {}",
+                index,
+                BlockKind::from(Some(params)),
+                params.z_divisor,
+                params.x_offset,
+                params.y_offset
+            ),
+            None => println!(
+                "block {}: not an Advent of Code block, {} instructions",
+                index,
+                block.len()
+            ),
+        }
+        for compound_bool in detect_compound_booleans(block) {
+            println!(
+                "  instruction {}: {} = ({} + {}) != {}",
+                compound_bool.start_index,
+                compound_bool.register,
+                compound_bool.register,
+                compound_bool.addend,
+                compound_bool.compared_with
+            );
+        }
+    }
+
+    for (first, second) in find_duplicate_blocks(program) {
+        println!("blocks {} and {} are identical", first, second);
+    }
 
-    reversed_args
-        .pop()
-        .expect("Expected the executable name to be the first argument, but was missing");
+    let kinds = classify_blocks(program);
+    println!("stack balanced: {}", is_stack_balanced(&kinds));
+    if let Some(bound) = max_z_bound(program) {
+        println!("z never exceeds {} for a valid model number", bound);
+    }
+}
+
+fn print_dataflow(program: &[Instruction]) {
+    for (input, registers) in input_influence_map(program) {
+        if registers.is_empty() {
+            println!("input {} flows into no register", input);
+        } else {
+            println!("input {} flows into: {}", input, registers.iter().join(" "));
+        }
+    }
+
+    match last_relevant_input(program) {
+        Some(input) => println!("last input affecting the output: {}", input),
+        None => println!("no input affects the output"),
+    }
+    println!(
+        "longest dependency chain into the output: {} instructions",
+        critical_path_length(program)
+    );
+    for load in find_constant_loads(program) {
+        println!(
+            "instruction {}: loads constant {} into {}",
+            load.index, load.value, load.register
+        );
+    }
+}
 
-    let part = reversed_args.pop().expect("part number");
-    let input_file = reversed_args.pop().expect("input file");
-    let content = fs::read_to_string(input_file).unwrap();
+/// Write the program's binary encoding, which `*.bin` inputs are read back from.
+#[cfg(feature = "binary")]
+fn encode(program: &[Instruction], out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    out.write_all(&binary::to_bytes(program))?;
+    Ok(())
+}
 
-    let input_program: Vec<Instruction> = parse_program(content.as_str());
+#[cfg(not(feature = "binary"))]
+fn encode(_program: &[Instruction], _out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    Err("the encode command needs a build with the `binary` feature".into())
+}
 
-    match part {
-        "analyze" => {
-            let analysis = analyze_program(input_program);
-            println!("{:?}", analysis);
+/// The default pipeline, without the passes named by `--skip-pass`.
+fn build_pipeline(skip_passes: &[String]) -> Result<Pipeline, String> {
+    let default_pipeline = Pipeline::default();
+    let mut pipeline = default_pipeline.clone();
+    for name in skip_passes {
+        if !default_pipeline.pass_names().any(|known| known == name) {
+            return Err(format!(
+                "unknown pass: {} (expected one of: {})",
+                name,
+                default_pipeline.pass_names().join(", ")
+            ));
         }
-        _ => unreachable!("{}", part),
+        pipeline = pipeline.without_pass(name);
     }
+    Ok(pipeline)
 }
 
-fn analyze_program(input_program: Vec<Instruction>) -> Vec<Instruction> {
-    input_program
+#[cfg(test)]
+mod tests {
+    use crate::cli::Cli;
+
+    use super::{build_pipeline, run};
+
+    #[test]
+    fn time_is_supported_in_every_mode() {
+        let modes: [&[&str]; 5] = [
+            &["analyze"],
+            &["analyze", "--compare-passes"],
+            &["blocks"],
+            &["dataflow"],
+            &["count"],
+        ];
+        for mode in modes {
+            let args = mode
+                .iter()
+                .chain(&["sample_programs/aoc_challenge.txt", "--time"]);
+            let cli = Cli::parse(args).unwrap();
+            assert!(cli.time);
+            run(cli).unwrap();
+        }
+    }
+
+    #[test]
+    fn skipped_passes_are_removed_from_the_pipeline() {
+        let pipeline = build_pipeline(&[]).unwrap();
+        assert_eq!(
+            vec!["peephole", "remove-trailing-dead"],
+            pipeline.pass_names().collect::<Vec<_>>()
+        );
+
+        let skipped = ["peephole".to_string()];
+        let pipeline = build_pipeline(&skipped).unwrap();
+        assert_eq!(
+            vec!["remove-trailing-dead"],
+            pipeline.pass_names().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn skipping_unknown_pass_is_an_error() {
+        let skipped = ["peephole".to_string(), "inline".to_string()];
+        assert_eq!(
+            "unknown pass: inline (expected one of: peephole, remove-trailing-dead)",
+            build_pipeline(&skipped).err().unwrap()
+        );
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn encoded_program_is_read_back_from_bin_input() {
+        use std::{env, fs};
+
+        use super::{encode, Input};
+
+        let text_input = Input::read("sample_programs/aoc_challenge.txt".as_ref()).unwrap();
+        let program = text_input.program(None).unwrap();
+        let mut bytes = vec![];
+        encode(&program, &mut bytes).unwrap();
+
+        let path = env::temp_dir().join(format!("monad_compiler_{}.bin", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        let binary_input = Input::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(program, binary_input.program(None).unwrap());
+        assert_eq!(
+            text_input.count(Some(20)).unwrap(),
+            binary_input.count(Some(20)).unwrap()
+        );
+        assert_eq!(
+            text_input.programs(None).unwrap(),
+            binary_input.programs(None).unwrap()
+        );
+    }
+
+    #[cfg(not(feature = "binary"))]
+    #[test]
+    fn encode_needs_the_binary_feature() {
+        let cli = Cli::parse(["encode", "sample_programs/aoc_challenge.txt"]).unwrap();
+        assert_eq!(
+            "the encode command needs a build with the `binary` feature",
+            run(cli).unwrap_err().to_string()
+        );
+    }
 }
@@ -0,0 +1,492 @@
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use log::{debug, trace};
+
+use crate::program::{materialize_constant, Instruction, Operand, Register, OUTPUT_REGISTER};
+
+/// Remove all non-input instructions after the last write to the output register.
+/// Nothing that runs after the output register's final write can affect the output,
+/// so such instructions are dead weight. Input instructions are kept regardless,
+/// so that the program still consumes the same number of inputs.
+pub fn remove_trailing_dead_instructions(program: Vec<Instruction>) -> Vec<Instruction> {
+    let original_len = program.len();
+    debug!(
+        "remove_trailing_dead_instructions: start, {} instructions",
+        original_len
+    );
+
+    let last_output_write = program
+        .iter()
+        .rposition(|instr| instr.destination() == OUTPUT_REGISTER);
+
+    let live_prefix_len = last_output_write.map_or(0, |index| index + 1);
+
+    let result: Vec<_> = program
+        .into_iter()
+        .enumerate()
+        .filter(|(index, instr)| {
+            let keep = *index < live_prefix_len || matches!(instr, Instruction::Input(_));
+            if !keep {
+                trace!("removing dead instruction {}: {}", index, instr);
+            }
+            keep
+        })
+        .map(|(_, instr)| instr)
+        .collect();
+
+    debug!(
+        "remove_trailing_dead_instructions: end, removed {} of {} instructions",
+        original_len - result.len(),
+        original_len
+    );
+    result
+}
+
+/// Simplify short, fixed patterns of adjacent instructions:
+/// - `mul r 0` directly after another `mul r 0` is dropped, since `r` is already zero.
+/// - `mul r k` directly followed by `div r k` for a nonzero literal `k` is dropped entirely,
+///   since dividing an exact multiple of `k` by `k` gives back the original value.
+///   This rule assumes that `r * k` doesn't overflow. No other pass makes that assumption,
+///   so the pass is only correct for programs whose values stay far from the `i64` limits.
+pub fn peephole(program: Vec<Instruction>) -> Vec<Instruction> {
+    let original_len = program.len();
+    debug!("peephole: start, {} instructions", original_len);
+
+    let mut result: Vec<Instruction> = Vec::with_capacity(original_len);
+    for (index, instr) in program.into_iter().enumerate() {
+        let is_repeated_clear = matches!(instr, Instruction::Mul(_, Operand::Literal(0)))
+            && result.last() == Some(&instr);
+        let is_multiply_divide_round_trip = match instr {
+            Instruction::Div(r, Operand::Literal(k)) if k != 0 => {
+                result.last() == Some(&Instruction::Mul(r, Operand::Literal(k)))
+            }
+            _ => false,
+        };
+        if is_repeated_clear {
+            trace!("removing repeated clear {}: {}", index, instr);
+        } else if is_multiply_divide_round_trip {
+            let multiply = result
+                .pop()
+                .expect("round trip starts with a multiplication");
+            trace!(
+                "removing multiply-divide round trip ending at {}: {}; {}",
+                index,
+                multiply,
+                instr
+            );
+        } else {
+            result.push(instr);
+        }
+    }
+
+    debug!(
+        "peephole: end, removed {} of {} instructions",
+        original_len - result.len(),
+        original_len
+    );
+    result
+}
+
+/// A `mul r 0` immediately followed by `add r <value>`, which loads a constant into `r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstantLoad {
+    pub index: usize, // index of the mul instruction
+    pub register: Register,
+    pub value: i64,
+}
+
+/// Find all places where the program loads a constant into a register using
+/// the `materialize_constant()` instruction sequence.
+pub fn find_constant_loads(program: &[Instruction]) -> Vec<ConstantLoad> {
+    program
+        .windows(2)
+        .enumerate()
+        .filter_map(|(index, window)| match *window {
+            [Instruction::Mul(register, _), Instruction::Add(_, Operand::Literal(value))]
+                if window == materialize_constant(register, value) =>
+            {
+                Some(ConstantLoad {
+                    index,
+                    register,
+                    value,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// An optimization pass that rewrites a program into an equivalent one.
+pub trait Pass {
+    /// A short, unique name identifying the pass.
+    fn name(&self) -> &'static str;
+
+    fn run(&self, program: Vec<Instruction>) -> Vec<Instruction>;
+}
+
+/// The pass form of `remove_trailing_dead_instructions()`.
+pub struct RemoveTrailingDeadInstructions;
+
+impl Pass for RemoveTrailingDeadInstructions {
+    fn name(&self) -> &'static str {
+        "remove-trailing-dead"
+    }
+
+    fn run(&self, program: Vec<Instruction>) -> Vec<Instruction> {
+        remove_trailing_dead_instructions(program)
+    }
+}
+
+/// The pass form of `peephole()`.
+pub struct Peephole;
+
+impl Pass for Peephole {
+    fn name(&self) -> &'static str {
+        "peephole"
+    }
+
+    fn run(&self, program: Vec<Instruction>) -> Vec<Instruction> {
+        peephole(program)
+    }
+}
+
+/// An ordered sequence of optimization passes, each run once on the previous pass' output.
+#[derive(Clone)]
+pub struct Pipeline {
+    passes: Vec<Rc<dyn Pass>>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::empty()
+            .with_pass(Peephole)
+            .with_pass(RemoveTrailingDeadInstructions)
+    }
+}
+
+impl Pipeline {
+    /// A pipeline with no passes, which leaves programs unchanged.
+    pub fn empty() -> Self {
+        Self { passes: vec![] }
+    }
+
+    /// Append a pass to the end of the pipeline.
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Rc::new(pass));
+        self
+    }
+
+    /// Remove all passes with the given name from the pipeline.
+    pub fn without_pass(mut self, name: &str) -> Self {
+        self.passes.retain(|pass| pass.name() != name);
+        self
+    }
+
+    pub fn pass_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// For each pass, the optimized program's length when that pass alone is skipped.
+    /// Comparing these to the full pipeline's result shows each pass' marginal benefit.
+    pub fn ablate(&self, program: &[Instruction]) -> Vec<(&'static str, usize)> {
+        self.pass_names()
+            .map(|name| {
+                let ablated = self.clone().without_pass(name);
+                (name, ablated.run(program.to_vec()).len())
+            })
+            .collect()
+    }
+
+    pub fn run(&self, program: Vec<Instruction>) -> Vec<Instruction> {
+        self.run_timed(program).0
+    }
+
+    /// Run the pipeline, also reporting the wall-clock time each pass took.
+    pub fn run_timed(
+        &self,
+        program: Vec<Instruction>,
+    ) -> (Vec<Instruction>, Vec<(&'static str, Duration)>) {
+        let mut timings = vec![];
+        let result = self.passes.iter().fold(program, |program, pass| {
+            debug!("pipeline: running pass {}", pass.name());
+            let start = Instant::now();
+            let program = pass.run(program);
+            timings.push((pass.name(), start.elapsed()));
+            program
+        });
+        (result, timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, sync::Once};
+
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    use crate::{
+        parser::parse_program,
+        program::{Instruction, Register},
+    };
+
+    use super::{
+        find_constant_loads, peephole, remove_trailing_dead_instructions, ConstantLoad, Pass,
+        Pipeline,
+    };
+
+    thread_local! {
+        static CAPTURED_RECORDS: RefCell<Vec<(Level, String)>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Records log messages into a per-thread buffer, so that concurrently-running tests
+    /// only ever see their own records.
+    struct CapturingLogger;
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            CAPTURED_RECORDS.with(|records| {
+                records
+                    .borrow_mut()
+                    .push((record.level(), record.args().to_string()))
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// A pass that appends an input instruction into the given register,
+    /// making it visible in the output which passes ran and in what order.
+    struct AppendInput(&'static str, Register);
+
+    impl Pass for AppendInput {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn run(&self, mut program: Vec<Instruction>) -> Vec<Instruction> {
+            program.push(Instruction::Input(self.1));
+            program
+        }
+    }
+
+    fn appending_pipeline() -> Pipeline {
+        Pipeline::empty()
+            .with_pass(AppendInput("first", Register(0)))
+            .with_pass(AppendInput("second", Register(1)))
+            .with_pass(AppendInput("first", Register(2)))
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger;
+    static INSTALL_LOGGER: Once = Once::new();
+
+    fn capture_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<(Level, String)>) {
+        INSTALL_LOGGER.call_once(|| {
+            log::set_logger(&LOGGER).expect("no other logger is installed in tests");
+            log::set_max_level(LevelFilter::Trace);
+        });
+        CAPTURED_RECORDS.with(|records| records.borrow_mut().clear());
+        let result = f();
+        let records = CAPTURED_RECORDS.with(|records| records.take());
+        (result, records)
+    }
+
+    #[test]
+    fn trailing_instructions_after_last_output_write_are_removed() {
+        let program = parse_program("inp w\nadd z w\nadd x 5\ninp y\nadd x 5\nmul y x\n").unwrap();
+        let expected = parse_program("inp w\nadd z w\ninp y\n").unwrap();
+        assert_eq!(expected, remove_trailing_dead_instructions(program));
+    }
+
+    #[test]
+    fn program_ending_in_output_write_is_unchanged() {
+        let program = parse_program("inp w\nadd x w\nadd z x\n").unwrap();
+        assert_eq!(program.clone(), remove_trailing_dead_instructions(program));
+    }
+
+    #[test]
+    fn removing_dead_instructions_is_logged() {
+        let program = parse_program("inp w\nadd z w\nadd x 5\n").unwrap();
+        let (_, records) = capture_logs(|| remove_trailing_dead_instructions(program));
+        assert_eq!(
+            vec![
+                (
+                    Level::Debug,
+                    "remove_trailing_dead_instructions: start, 3 instructions".to_string()
+                ),
+                (
+                    Level::Trace,
+                    "removing dead instruction 2: add x 5".to_string()
+                ),
+                (
+                    Level::Debug,
+                    "remove_trailing_dead_instructions: end, removed 1 of 3 instructions"
+                        .to_string()
+                ),
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn empty_pipeline_leaves_program_unchanged() {
+        let program = parse_program("inp w\nadd x 5\nmul x 0\nmul x 0\n").unwrap();
+        assert_eq!(program.clone(), Pipeline::empty().run(program));
+    }
+
+    #[test]
+    fn passes_run_in_the_order_given() {
+        let pipeline = appending_pipeline();
+        assert_eq!(
+            vec!["first", "second", "first"],
+            pipeline.pass_names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            parse_program("inp w\ninp x\ninp y\n").unwrap(),
+            pipeline.run(vec![])
+        );
+    }
+
+    #[test]
+    fn without_pass_removes_every_pass_with_that_name() {
+        let pipeline = appending_pipeline().without_pass("first");
+        assert_eq!(vec!["second"], pipeline.pass_names().collect::<Vec<_>>());
+        assert_eq!(parse_program("inp x\n").unwrap(), pipeline.run(vec![]));
+
+        let pipeline = appending_pipeline().without_pass("missing");
+        assert_eq!(3, pipeline.pass_names().count());
+    }
+
+    #[test]
+    fn sample_examples_are_optimized_for_output_in_z_only() {
+        // The examples report results in registers other than z,
+        // so the default pipeline is free to remove the instructions computing them.
+        let negate = parse_program(include_str!("../sample_programs/aoc_example_negate.txt"));
+        assert_eq!(
+            parse_program("inp x\n").unwrap(),
+            Pipeline::default().run(negate.unwrap())
+        );
+
+        let binary = parse_program(include_str!("../sample_programs/aoc_example_binary.txt"));
+        assert_eq!(
+            parse_program("inp w\nadd z w\nmod z 2\n").unwrap(),
+            Pipeline::default().run(binary.unwrap())
+        );
+
+        let three_times = parse_program(include_str!(
+            "../sample_programs/aoc_example_three_times.txt"
+        ));
+        let three_times = three_times.unwrap();
+        assert_eq!(three_times.clone(), Pipeline::default().run(three_times));
+    }
+
+    #[test]
+    fn run_timed_reports_every_pass_in_order() {
+        let (program, timings) = appending_pipeline().run_timed(vec![]);
+        assert_eq!(appending_pipeline().run(vec![]), program);
+        assert_eq!(
+            vec!["first", "second", "first"],
+            timings.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn running_each_pass_is_logged() {
+        let pipeline = appending_pipeline().without_pass("first");
+        let (_, records) = capture_logs(|| pipeline.run(vec![]));
+        assert_eq!(
+            vec![(Level::Debug, "pipeline: running pass second".to_string())],
+            records
+        );
+    }
+
+    #[test]
+    fn ablate_shows_the_benefit_of_each_pass() {
+        let program = parse_program("inp w\nmul x 0\nmul x 0\nadd z x\nadd x 5\n").unwrap();
+        let pipeline = Pipeline::default();
+        assert_eq!(3, pipeline.run(program.clone()).len());
+        assert_eq!(
+            vec![("peephole", 4), ("remove-trailing-dead", 4)],
+            pipeline.ablate(&program)
+        );
+    }
+
+    #[test]
+    fn repeated_clear_becomes_one_instruction() {
+        let program = parse_program("mul x 0\nmul x 0\nmul x 0\n").unwrap();
+        assert_eq!(parse_program("mul x 0\n").unwrap(), peephole(program));
+    }
+
+    #[test]
+    fn clears_of_different_or_modified_registers_are_kept() {
+        let program = parse_program("mul x 0\nmul y 0\n").unwrap();
+        assert_eq!(program.clone(), peephole(program));
+
+        let program = parse_program("mul x 0\nadd x 1\nmul x 0\n").unwrap();
+        assert_eq!(program.clone(), peephole(program));
+    }
+
+    #[test]
+    fn finds_constant_loads() {
+        let program = parse_program("inp w\nmul x 0\nadd x 5\nmul y 0\nadd y -1\n").unwrap();
+        let [_, x, y, _] = Register::ALL;
+        assert_eq!(
+            vec![
+                ConstantLoad {
+                    index: 1,
+                    register: x,
+                    value: 5
+                },
+                ConstantLoad {
+                    index: 3,
+                    register: y,
+                    value: -1
+                },
+            ],
+            find_constant_loads(&program)
+        );
+    }
+
+    #[test]
+    fn add_to_another_register_is_not_a_constant_load() {
+        let program =
+            parse_program("mul x 0\nadd y 5\nmul x 2\nadd x 5\nmul x 0\nadd x w\n").unwrap();
+        assert!(find_constant_loads(&program).is_empty());
+    }
+
+    #[test]
+    fn multiply_divide_round_trip_is_removed() {
+        let program = parse_program("inp x\nmul x 26\ndiv x 26\nadd z x\n").unwrap();
+        assert_eq!(
+            parse_program("inp x\nadd z x\n").unwrap(),
+            peephole(program)
+        );
+
+        let program = parse_program("inp x\nmul x -3\ndiv x -3\n").unwrap();
+        assert_eq!(parse_program("inp x\n").unwrap(), peephole(program));
+    }
+
+    #[test]
+    fn multiply_divide_by_different_or_zero_factor_is_kept() {
+        let program = parse_program("inp x\nmul x 26\ndiv x 13\n").unwrap();
+        assert_eq!(program.clone(), peephole(program));
+
+        let program = parse_program("inp x\nmul x 0\ndiv x 0\n").unwrap();
+        assert_eq!(program.clone(), peephole(program));
+
+        let program = parse_program("inp x\nmul x 26\ndiv y 26\n").unwrap();
+        assert_eq!(program.clone(), peephole(program));
+    }
+
+    #[test]
+    fn clear_after_removed_round_trip() {
+        let program = parse_program("mul x 0\nmul x 26\ndiv x 26\nmul x 0\n").unwrap();
+        assert_eq!(parse_program("mul x 0\n").unwrap(), peephole(program));
+    }
+}
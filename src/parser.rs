@@ -1,32 +1,63 @@
+use std::fmt::Display;
+
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, digit1, line_ending, one_of, space1},
-    combinator::{map, map_res, opt, recognize},
-    multi::many1,
-    sequence::tuple,
+    bytes::complete::{tag, take_while},
+    character::complete::{one_of, satisfy, space1},
+    combinator::{cut, map, map_opt, map_res, opt, recognize},
+    sequence::{pair, preceded, tuple},
     IResult,
 };
 
 use crate::program::{Register, Operand, Instruction};
 
 fn register(input: &str) -> IResult<&str, Register> {
-    let (remainder, matched_char) = one_of("wxyz")(input)?;
-    let register_id = match matched_char {
-        'w' => 0,
-        'x' => 1,
-        'y' => 2,
-        'z' => 3,
-        _ => unreachable!("{}", matched_char),
-    };
+    map_res(
+        satisfy(|c| Register::try_from(c).is_ok()),
+        Register::try_from,
+    )(input)
+}
 
-    Ok((remainder, Register(register_id)))
+/// Digits in the given radix, optionally separated by underscores as in `1_000`.
+/// The first character must be a digit.
+fn radix_digits(radix: u32) -> impl FnMut(&str) -> IResult<&str, (u32, &str)> {
+    move |input| {
+        map(
+            recognize(pair(
+                satisfy(|c| c.is_digit(radix)),
+                take_while(|c: char| c.is_digit(radix) || c == '_'),
+            )),
+            |digits| (radix, digits),
+        )(input)
+    }
 }
 
+/// A signed integer literal: decimal by default, or hexadecimal and binary
+/// with the `0x` and `0b` prefixes. Digits may be separated by underscores.
+/// An explicit leading `+` sign is allowed, but at most one sign may be given.
+/// A radix prefix must be followed by digits in that radix, so `0xG` is an error
+/// rather than a `0` followed by the unexpected token `xG`.
 fn text_signed_int(input: &str) -> IResult<&str, i64> {
-    map_res(recognize(tuple((opt(char('-')), digit1))), |value: &str| {
-        value.parse()
-    })(input)
+    map_opt(
+        tuple((
+            opt(one_of("+-")),
+            alt((
+                preceded(tag("0x"), cut(radix_digits(16))),
+                preceded(tag("0b"), cut(radix_digits(2))),
+                radix_digits(10),
+            )),
+        )),
+        |(sign, (radix, digits))| {
+            let digits: String = digits.chars().filter(|c| *c != '_').collect();
+            let magnitude = i128::from_str_radix(&digits, radix).ok()?;
+            let value = if sign == Some('-') {
+                -magnitude
+            } else {
+                magnitude
+            };
+            i64::try_from(value).ok()
+        },
+    )(input)
 }
 
 fn operand(input: &str) -> IResult<&str, Operand> {
@@ -38,10 +69,9 @@ fn operand(input: &str) -> IResult<&str, Operand> {
 }
 
 fn input_instruction(input: &str) -> IResult<&str, Instruction> {
-    map(
-        tuple((tag("inp"), space1, register, opt(line_ending))),
-        |(_, _, reg, _)| Instruction::Input(reg),
-    )(input)
+    map(tuple((tag("inp"), space1, register)), |(_, _, reg)| {
+        Instruction::Input(reg)
+    })(input)
 }
 
 fn binary_instruction(input: &str) -> IResult<&str, Instruction> {
@@ -52,9 +82,8 @@ fn binary_instruction(input: &str) -> IResult<&str, Instruction> {
             register,
             space1,
             operand,
-            opt(line_ending),
         )),
-        |(instr, _, reg, _, val, _)| match instr {
+        |(instr, _, reg, _, val)| match instr {
             "add" => Instruction::Add(reg, val),
             "mul" => Instruction::Mul(reg, val),
             "div" => Instruction::Div(reg, val),
@@ -69,8 +98,419 @@ fn instruction(input: &str) -> IResult<&str, Instruction> {
     alt((input_instruction, binary_instruction))(input)
 }
 
-pub fn parse_program(input: &str) -> Vec<Instruction> {
-    let (remainder, program) = many1(instruction)(input).unwrap();
-    assert!(remainder.is_empty());
-    program
+/// An error encountered while parsing a MONAD program.
+/// Line numbers are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidInstruction {
+        line: usize,
+        text: String,
+    },
+    UnexpectedToken {
+        line: usize,
+        after: &'static str,
+        token: String,
+    },
+    EmptyInstruction {
+        line: usize,
+    },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidInstruction { line, text } => {
+                write!(f, "invalid instruction on line {}: {:?}", line, text)
+            }
+            ParseError::UnexpectedToken { line, after, token } => {
+                write!(
+                    f,
+                    "unexpected token after {} on line {}: {:?}",
+                    after, line, token
+                )
+            }
+            ParseError::EmptyInstruction { line } => {
+                write!(
+                    f,
+                    "empty instruction between `;` separators on line {}",
+                    line
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn single_instruction(line_number: usize, text: &str) -> Result<Instruction, ParseError> {
+    let (remainder, instr) = instruction(text).map_err(|_| ParseError::InvalidInstruction {
+        line: line_number,
+        text: text.to_string(),
+    })?;
+
+    // Trailing whitespace is harmless, but anything else after the instruction is an error.
+    let remainder = remainder.trim();
+    if remainder.is_empty() {
+        Ok(instr)
+    } else {
+        Err(ParseError::UnexpectedToken {
+            line: line_number,
+            after: match instr {
+                Instruction::Input(_) => "register",
+                _ => "operand",
+            },
+            token: remainder.to_string(),
+        })
+    }
+}
+
+/// Parse one line of a program. A line may hold several instructions separated by `;`,
+/// optionally with whitespace around the separator, as in `inp w; add x 2`.
+/// A single trailing `;` is allowed, as in `inp w;`. Blank lines hold no instructions.
+fn instruction_line(line_number: usize, line: &str) -> Result<Vec<Instruction>, ParseError> {
+    if line.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut segments: Vec<&str> = line.split(';').map(str::trim).collect();
+    if segments.len() > 1 && segments.last() == Some(&"") {
+        segments.pop();
+    }
+
+    segments
+        .into_iter()
+        .map(|segment| {
+            if segment.is_empty() {
+                Err(ParseError::EmptyInstruction { line: line_number })
+            } else {
+                single_instruction(line_number, segment)
+            }
+        })
+        .collect()
+}
+
+pub fn parse_program(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut program = vec![];
+    for (index, line) in input.lines().enumerate() {
+        program.extend(instruction_line(index + 1, line)?);
+    }
+    Ok(program)
+}
+
+/// Parse only the first `limit` instructions of a program.
+/// Lines after the limit is reached are not parsed, so they can't cause errors.
+pub fn parse_program_prefix(input: &str, limit: usize) -> Result<Vec<Instruction>, ParseError> {
+    let mut program = vec![];
+    for (index, line) in input.lines().enumerate() {
+        if program.len() >= limit {
+            break;
+        }
+        program.extend(instruction_line(index + 1, line)?);
+    }
+    program.truncate(limit);
+    Ok(program)
+}
+
+/// Count a program's instructions and input instructions, in that order,
+/// without building the whole program in memory.
+/// Given a limit, only the first `limit` instructions are parsed and counted,
+/// as in `parse_program_prefix()`.
+pub fn count_instructions(input: &str, limit: Option<usize>) -> Result<(usize, usize), ParseError> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let mut instruction_count = 0;
+    let mut input_count = 0;
+    for (index, line) in input.lines().enumerate() {
+        if instruction_count >= limit {
+            break;
+        }
+        for instr in instruction_line(index + 1, line)?
+            .into_iter()
+            .take(limit - instruction_count)
+        {
+            instruction_count += 1;
+            if let Instruction::Input(_) = instr {
+                input_count += 1;
+            }
+        }
+    }
+    Ok((instruction_count, input_count))
+}
+
+fn is_program_delimiter(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line == "---"
+}
+
+/// Parse several programs separated by blank lines or `---` lines.
+/// Empty segments, such as those created by leading or trailing delimiters, are skipped.
+/// Line numbers in errors refer to the whole input, not the individual program.
+/// Given a limit, only the first `limit` instructions of each program are parsed,
+/// as in `parse_program_prefix()`.
+pub fn parse_programs(
+    input: &str,
+    limit: Option<usize>,
+) -> Result<Vec<Vec<Instruction>>, ParseError> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let mut programs = vec![];
+    let mut current_program = vec![];
+    for (index, line) in input.lines().enumerate() {
+        if is_program_delimiter(line) {
+            if !current_program.is_empty() {
+                current_program.truncate(limit);
+                programs.push(std::mem::take(&mut current_program));
+            }
+        } else if current_program.len() < limit {
+            current_program.extend(instruction_line(index + 1, line)?);
+        }
+    }
+    if !current_program.is_empty() {
+        current_program.truncate(limit);
+        programs.push(current_program);
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::program::{Instruction, Operand, Register};
+
+    use super::{
+        count_instructions, parse_program, parse_program_prefix, parse_programs, text_signed_int,
+        ParseError,
+    };
+
+    #[test]
+    fn extra_token_after_operand() {
+        let error = parse_program("inp w\nadd x 2 extra\n").unwrap_err();
+        assert_eq!(
+            ParseError::UnexpectedToken {
+                line: 2,
+                after: "operand",
+                token: "extra".to_string()
+            },
+            error
+        );
+        assert_eq!(
+            "unexpected token after operand on line 2: \"extra\"",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn extra_token_after_input_register() {
+        let error = parse_program("inp w x\n").unwrap_err();
+        assert_eq!(
+            ParseError::UnexpectedToken {
+                line: 1,
+                after: "register",
+                token: "x".to_string()
+            },
+            error
+        );
+        assert_eq!(
+            "unexpected token after register on line 1: \"x\"",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn trailing_whitespace_is_ignored() {
+        assert_eq!(
+            vec![
+                Instruction::Add(Register(1), Operand::Literal(2)),
+                Instruction::Input(Register(0)),
+            ],
+            parse_program("add x 2 \ninp w\t\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn programs_separated_by_delimiters() {
+        let input = "inp w\nadd z w\n---\ninp x\n\ninp y\nmul y 2\n---\n";
+        let expected = vec![
+            parse_program("inp w\nadd z w\n").unwrap(),
+            parse_program("inp x\n").unwrap(),
+            parse_program("inp y\nmul y 2\n").unwrap(),
+        ];
+        assert_eq!(expected, parse_programs(input, None).unwrap());
+    }
+
+    #[test]
+    fn repeated_and_leading_delimiters_create_no_empty_programs() {
+        let input = "---\n\ninp w\n---\n---\n \ninp x\n";
+        let expected = vec![
+            parse_program("inp w\n").unwrap(),
+            parse_program("inp x\n").unwrap(),
+        ];
+        assert_eq!(expected, parse_programs(input, None).unwrap());
+        assert!(parse_programs("---\n\n", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn programs_error_line_numbers_refer_to_whole_input() {
+        assert_eq!(
+            ParseError::InvalidInstruction {
+                line: 3,
+                text: "sub x 1".to_string()
+            },
+            parse_programs("inp w\n---\nsub x 1\n", None).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn literals_in_every_radix() {
+        assert_eq!(Ok(("", 26)), text_signed_int("0x1a"));
+        assert_eq!(Ok(("", 26)), text_signed_int("0x1A"));
+        assert_eq!(Ok(("", -26)), text_signed_int("-0x1a"));
+        assert_eq!(Ok(("", 5)), text_signed_int("0b101"));
+        assert_eq!(Ok(("", -5)), text_signed_int("-0b101"));
+        assert_eq!(Ok(("", 1_000_000)), text_signed_int("1_000_000"));
+        assert_eq!(Ok(("", 0xff)), text_signed_int("0xf_f"));
+    }
+
+    #[test]
+    fn literals_at_the_limits_of_i64() {
+        assert_eq!(Ok(("", i64::MIN)), text_signed_int("-9223372036854775808"));
+        assert_eq!(Ok(("", i64::MIN)), text_signed_int("-0x8000000000000000"));
+        assert_eq!(Ok(("", i64::MAX)), text_signed_int("9223372036854775807"));
+        assert!(text_signed_int("9223372036854775808").is_err());
+        assert!(text_signed_int("-9223372036854775809").is_err());
+    }
+
+    #[test]
+    fn invalid_literals_are_rejected() {
+        assert!(text_signed_int("0xG").is_err());
+        assert!(text_signed_int("0b2").is_err());
+        assert!(text_signed_int("_1").is_err());
+        assert_eq!(
+            ParseError::InvalidInstruction {
+                line: 1,
+                text: "add x 0xG".to_string()
+            },
+            parse_program("add x 0xG\n").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn explicit_plus_sign() {
+        assert_eq!(Ok(("", 5)), text_signed_int("+5"));
+        assert_eq!(Ok(("", 0)), text_signed_int("+0"));
+        assert_eq!(Ok(("", 26)), text_signed_int("+0x1a"));
+        assert!(text_signed_int("+-2").is_err());
+        assert!(text_signed_int("++2").is_err());
+        assert!(text_signed_int("--2").is_err());
+        assert!(parse_program("add x +-2\n").is_err());
+    }
+
+    #[test]
+    fn several_instructions_on_one_line() {
+        assert_eq!(
+            vec![
+                Instruction::Input(Register(0)),
+                Instruction::Add(Register(1), Operand::Literal(2)),
+                Instruction::Mul(Register(1), Operand::Literal(3)),
+            ],
+            parse_program("inp w; add x 2;mul x 3\n").unwrap()
+        );
+        assert_eq!(
+            parse_program("inp w\nadd x 2\n").unwrap(),
+            parse_program("inp w\t;\tadd x 2\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn trailing_separator_is_allowed() {
+        assert_eq!(
+            parse_program("inp w\nadd x 2\n").unwrap(),
+            parse_program("inp w;\nadd x 2; \n").unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_instruction_between_separators() {
+        let error = parse_program("inp w\ninp w;; add x 2\n").unwrap_err();
+        assert_eq!(ParseError::EmptyInstruction { line: 2 }, error);
+        assert_eq!(
+            "empty instruction between `;` separators on line 2",
+            error.to_string()
+        );
+        assert_eq!(
+            ParseError::EmptyInstruction { line: 1 },
+            parse_program(";\n").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn count_aoc_challenge() {
+        let input = include_str!("../sample_programs/aoc_challenge.txt");
+        assert_eq!(Ok((252, 14)), count_instructions(input, None));
+        assert_eq!(Ok((252, 14)), count_instructions(input, Some(1000)));
+        assert_eq!(Ok((19, 2)), count_instructions(input, Some(19)));
+        assert_eq!(Ok((0, 0)), count_instructions(input, Some(0)));
+    }
+
+    #[test]
+    fn count_reports_parse_errors() {
+        assert_eq!(
+            Err(ParseError::InvalidInstruction {
+                line: 2,
+                text: "sub x 1".to_string()
+            }),
+            count_instructions("inp w\nsub x 1\n", None)
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        assert_eq!(
+            parse_program("inp w\nadd x 2\n").unwrap(),
+            parse_program("\ninp w\n\n \t\nadd x 2\n\n").unwrap()
+        );
+        assert_eq!(Ok((2, 1)), count_instructions("inp w\n\nadd x 2\n\n", None));
+        assert_eq!(
+            ParseError::InvalidInstruction {
+                line: 3,
+                text: "sub x 1".to_string()
+            },
+            parse_program("inp w\n\nsub x 1\n").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn limit_keeps_only_the_first_instructions() {
+        let input = "inp w\nadd x w\nmul x 2\nadd z x\n";
+        let expected = parse_program("inp w\nadd x w\n").unwrap();
+        assert_eq!(expected, parse_program_prefix(input, 2).unwrap());
+        assert_eq!(Ok((2, 1)), count_instructions(input, Some(2)));
+    }
+
+    #[test]
+    fn limit_counts_instructions_not_lines() {
+        let input = "inp w; add x w\nmul x 2\n";
+        let expected = parse_program("inp w\nadd x w\nmul x 2\n").unwrap();
+        assert_eq!(expected[..1], parse_program_prefix(input, 1).unwrap());
+        assert_eq!(expected, parse_program_prefix(input, 10).unwrap());
+        assert_eq!(Ok((1, 1)), count_instructions(input, Some(1)));
+    }
+
+    #[test]
+    fn lines_past_the_limit_are_not_parsed() {
+        for input in ["inp w\nsub\n", "inp w\ninp x\nsub\n", "inp w; inp x\nsub\n"] {
+            assert_eq!(1, parse_program_prefix(input, 1).unwrap().len());
+            assert_eq!(Ok((1, 1)), count_instructions(input, Some(1)));
+            assert!(parse_program(input).is_err());
+            assert!(count_instructions(input, None).is_err());
+        }
+    }
+
+    #[test]
+    fn limit_applies_to_each_program() {
+        let input = "inp w\nadd z w\nsub\n---\ninp x; add z x\nsub\n";
+        let expected = vec![
+            parse_program("inp w\nadd z w\n").unwrap(),
+            parse_program("inp x\nadd z x\n").unwrap(),
+        ];
+        assert_eq!(expected, parse_programs(input, Some(2)).unwrap());
+        assert!(parse_programs(input, Some(3)).is_err());
+    }
 }
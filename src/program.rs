@@ -4,27 +4,56 @@ use std::fmt::Display;
 
 /// A register in a MONAD instruction.
 /// Registers w, x, y, z are Register(0) through Register(3), respectively.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Register(pub usize);
 
+/// The register whose final value is the output of a MONAD program.
+pub const OUTPUT_REGISTER: Register = Register(3);
+
+/// The register letters, in register index order.
+const REGISTER_LETTERS: [char; 4] = ['w', 'x', 'y', 'z'];
+
+impl Register {
+    /// All registers, in index order: w, x, y, z.
+    pub const ALL: [Register; 4] = [Register(0), Register(1), Register(2), Register(3)];
+
+    /// The letter used to name this register in MONAD source code,
+    /// or `None` if the register index doesn't name one of the four registers.
+    pub fn letter(&self) -> Option<char> {
+        REGISTER_LETTERS.get(self.0).copied()
+    }
+}
+
+/// Iterate over all registers, in index order.
+pub fn all_registers() -> impl Iterator<Item = Register> {
+    Register::ALL.into_iter()
+}
+
+impl TryFrom<char> for Register {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        REGISTER_LETTERS
+            .iter()
+            .position(|letter| *letter == value)
+            .map(Register)
+            .ok_or(value)
+    }
+}
+
 impl Display for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let letter = match self.0 {
-            0 => "w",
-            1 => "x",
-            2 => "y",
-            3 => "z",
-            _ => unreachable!("{:?}", self),
-        };
-
-        write!(f, "{}", letter)
+        match self.letter() {
+            Some(letter) => write!(f, "{}", letter),
+            None => write!(f, "<invalid register {}>", self.0),
+        }
     }
 }
 
 /// The second operand of a MONAD instruction.
 /// Can be a literal number like the `2` in `add x 2`,
 /// or a register like the `y` in `add x y`.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operand {
     Literal(i64),
     Register(Register),
@@ -41,7 +70,7 @@ impl Display for Operand {
 
 /// An instruction in the MONAD language.
 /// See Advent of Code 2021 Day 24 for the spec: https://adventofcode.com/2021/day/24
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     Input(Register),           // e.g. inp x
     Add(Register, Operand),    // e.g. add x 2
@@ -88,6 +117,66 @@ impl Instruction {
             Instruction::Equal(_, o) => Some(*o),
         }
     }
+
+    /// A copy of the instruction with its operand replaced, keeping the opcode and destination.
+    /// Returns `None` for input instructions, since those don't have an operand.
+    pub fn with_operand(&self, operand: Operand) -> Option<Instruction> {
+        match *self {
+            Instruction::Input(_) => None,
+            Instruction::Add(r, _) => Some(Instruction::Add(r, operand)),
+            Instruction::Mul(r, _) => Some(Instruction::Mul(r, operand)),
+            Instruction::Div(r, _) => Some(Instruction::Div(r, operand)),
+            Instruction::Mod(r, _) => Some(Instruction::Mod(r, operand)),
+            Instruction::Equal(r, _) => Some(Instruction::Equal(r, operand)),
+        }
+    }
+
+    /// A copy of the instruction with its destination register replaced,
+    /// keeping the opcode and operand.
+    pub fn with_register(&self, register: Register) -> Instruction {
+        match *self {
+            Instruction::Input(_) => Instruction::Input(register),
+            Instruction::Add(_, o) => Instruction::Add(register, o),
+            Instruction::Mul(_, o) => Instruction::Mul(register, o),
+            Instruction::Div(_, o) => Instruction::Div(register, o),
+            Instruction::Mod(_, o) => Instruction::Mod(register, o),
+            Instruction::Equal(_, o) => Instruction::Equal(register, o),
+        }
+    }
+
+    /// A simple static estimate of the instruction's execution cost.
+    /// Division and modulo are assumed to be the most expensive, followed by multiplication.
+    pub fn cost(&self) -> u32 {
+        match self {
+            Instruction::Input(_) => 1,
+            Instruction::Add(..) => 1,
+            Instruction::Mul(..) => 2,
+            Instruction::Div(..) => 4,
+            Instruction::Mod(..) => 4,
+            Instruction::Equal(..) => 1,
+        }
+    }
+
+    /// Whether the instruction's operand is its own destination register, as in `add x x`.
+    #[inline]
+    pub fn reads_own_destination(&self) -> bool {
+        self.operand() == Some(Operand::Register(self.destination()))
+    }
+}
+
+/// The instructions that set `register` to the constant `value`, regardless of its prior value:
+/// `mul r 0` followed by `add r <value>`, with the `add` omitted when `value` is zero.
+pub fn materialize_constant(register: Register, value: i64) -> Vec<Instruction> {
+    let mut instrs = vec![Instruction::Mul(register, Operand::Literal(0))];
+    if value != 0 {
+        instrs.push(Instruction::Add(register, Operand::Literal(value)));
+    }
+    instrs
+}
+
+/// The total static cost of the program's instructions, per `Instruction::cost()`.
+pub fn program_cost(program: &[Instruction]) -> u32 {
+    program.iter().map(Instruction::cost).sum()
 }
 
 /// We can't impl `Display` for `&[Instruction]`, so we have to make a newtype for it.
@@ -95,15 +184,163 @@ pub struct InstructionStream<'a>(pub &'a [Instruction]);
 
 impl<'a> Display for InstructionStream<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for instr in self.0.iter() {
+        for instr in self {
             writeln!(f, "{}", instr)?;
         }
         Ok(())
     }
 }
 
+impl<'a> IntoIterator for &InstructionStream<'a> {
+    type Item = &'a Instruction;
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 impl<'a> From<&'a [Instruction]> for InstructionStream<'a> {
     fn from(x: &'a [Instruction]) -> Self {
         Self(x)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{
+        all_registers, materialize_constant, program_cost, Instruction, InstructionStream, Operand,
+        Register,
+    };
+
+    #[test]
+    fn register_from_valid_chars() {
+        for (index, letter) in ['w', 'x', 'y', 'z'].into_iter().enumerate() {
+            let register = Register::try_from(letter).unwrap();
+            assert_eq!(Register(index), register);
+            assert_eq!(Some(letter), register.letter());
+        }
+    }
+
+    #[test]
+    fn register_from_invalid_chars() {
+        for letter in ['a', 'v', 'W', '0', ' '] {
+            assert_eq!(Err(letter), Register::try_from(letter));
+        }
+    }
+
+    #[test]
+    fn out_of_range_register_has_no_letter() {
+        assert_eq!(None, Register(4).letter());
+        assert_eq!("<invalid register 4>", Register(4).to_string());
+    }
+
+    #[test]
+    fn materialize_zero_is_a_single_clear() {
+        assert_eq!(
+            vec![Instruction::Mul(Register(1), Operand::Literal(0))],
+            materialize_constant(Register(1), 0)
+        );
+    }
+
+    #[test]
+    fn materialize_nonzero_clears_then_adds() {
+        assert_eq!(
+            vec![
+                Instruction::Mul(Register(2), Operand::Literal(0)),
+                Instruction::Add(Register(2), Operand::Literal(-7)),
+            ],
+            materialize_constant(Register(2), -7)
+        );
+    }
+
+    #[test]
+    fn reads_own_destination() {
+        let x = Register(1);
+        assert!(Instruction::Add(x, Operand::Register(x)).reads_own_destination());
+        assert!(!Instruction::Add(x, Operand::Register(Register(2))).reads_own_destination());
+        assert!(!Instruction::Add(x, Operand::Literal(1)).reads_own_destination());
+        assert!(!Instruction::Input(x).reads_own_destination());
+    }
+
+    #[test]
+    fn all_registers_render_in_index_order() {
+        assert_eq!("w x y z", Register::ALL.iter().join(" "));
+        assert_eq!(Register::ALL.to_vec(), all_registers().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn with_operand_replaces_only_the_operand() {
+        let x = Register(1);
+        assert_eq!(
+            Some(Instruction::Add(x, Operand::Literal(3))),
+            Instruction::Add(x, Operand::Literal(2)).with_operand(Operand::Literal(3))
+        );
+        assert_eq!(
+            Some(Instruction::Equal(x, Operand::Register(Register(0)))),
+            Instruction::Equal(x, Operand::Literal(2)).with_operand(Operand::Register(Register(0)))
+        );
+        assert_eq!(
+            None,
+            Instruction::Input(x).with_operand(Operand::Literal(3))
+        );
+    }
+
+    #[test]
+    fn instruction_stream_iterates_over_its_instructions() {
+        let program = [
+            Instruction::Input(Register(0)),
+            Instruction::Add(Register(3), Operand::Register(Register(0))),
+        ];
+        let stream = InstructionStream(&program);
+        assert_eq!(2, (&stream).into_iter().count());
+        assert_eq!(
+            program.iter().collect::<Vec<_>>(),
+            (&stream).into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!("inp w\nadd z w\n", stream.to_string());
+    }
+
+    #[test]
+    fn cost_per_opcode() {
+        let x = Register(1);
+        let operand = Operand::Literal(2);
+        assert_eq!(1, Instruction::Input(x).cost());
+        assert_eq!(1, Instruction::Add(x, operand).cost());
+        assert_eq!(2, Instruction::Mul(x, operand).cost());
+        assert_eq!(4, Instruction::Div(x, operand).cost());
+        assert_eq!(4, Instruction::Mod(x, operand).cost());
+        assert_eq!(1, Instruction::Equal(x, operand).cost());
+    }
+
+    #[test]
+    fn program_cost_sums_instruction_costs() {
+        let x = Register(1);
+        let program = [
+            Instruction::Input(x),
+            Instruction::Div(x, Operand::Literal(26)),
+            Instruction::Mul(x, Operand::Register(x)),
+        ];
+        assert_eq!(7, program_cost(&program));
+        assert_eq!(0, program_cost(&[]));
+    }
+
+    #[test]
+    fn with_register_replaces_only_the_destination() {
+        let [w, x, y, _] = Register::ALL;
+        assert_eq!(
+            Instruction::Mod(y, Operand::Register(x)),
+            Instruction::Mod(x, Operand::Register(x)).with_register(y)
+        );
+        assert_eq!(
+            Instruction::Div(w, Operand::Literal(26)),
+            Instruction::Div(x, Operand::Literal(26)).with_register(w)
+        );
+        assert_eq!(
+            Instruction::Input(y),
+            Instruction::Input(w).with_register(y)
+        );
+    }
+}